@@ -1,6 +1,7 @@
 use crate::{Msg, MSG_BUS};
 use anyhow::{anyhow, bail, Context, Result};
-use cargo_metadata::{Artifact, Message};
+use cargo_metadata::{diagnostic::DiagnosticLevel, Artifact, CompilerMessage, Message};
+use command_group::AsyncCommandGroup;
 use log::LevelFilter;
 use serde::Deserialize;
 use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
@@ -8,14 +9,23 @@ use std::{
     fs,
     path::{Path, PathBuf},
     process::Stdio,
+    time::Duration,
 };
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    process::{Child, Command},
+    process::Command,
     sync::oneshot,
     task::JoinHandle,
 };
 
+#[cfg(unix)]
+use command_group::Signal;
+
+pub use command_group::AsyncGroupChild;
+
+/// Grace period after SIGTERM before escalating to SIGKILL.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
 pub fn setup_logging(verbose: u8) {
     let log_level = match verbose {
         0 => LevelFilter::Warn,
@@ -86,25 +96,139 @@ pub fn write(file: &str, text: &str) -> Result<()> {
     fs::write(&file, text).context(format!("write {file}"))
 }
 
-pub fn os_arch() -> Result<(&'static str, &'static str)> {
-    let target_os = if cfg!(target_os = "windows") {
-        "windows"
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TargetOs {
+    Windows,
+    Macos,
+    Linux,
+    Freebsd,
+    Netbsd,
+}
+
+impl TargetOs {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Windows => "windows",
+            Self::Macos => "macos",
+            Self::Linux => "linux",
+            Self::Freebsd => "freebsd",
+            Self::Netbsd => "netbsd",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TargetArch {
+    X86_64,
+    Aarch64,
+    Arm,
+    X86,
+}
+
+impl TargetArch {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::X86_64 => "x86_64",
+            Self::Aarch64 => "aarch64",
+            Self::Arm => "arm",
+            Self::X86 => "x86",
+        }
+    }
+}
+
+/// `None` for targets with no libc tag (macOS, BSDs).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TargetAbi {
+    Gnu,
+    Musl,
+    Msvc,
+    None,
+}
+
+impl TargetAbi {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gnu => "gnu",
+            Self::Musl => "musl",
+            Self::Msvc => "msvc",
+            Self::None => "none",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TargetTriple {
+    pub os: TargetOs,
+    pub arch: TargetArch,
+    pub abi: TargetAbi,
+}
+
+impl TargetTriple {
+    /// Renders the Rust target triple this platform matches.
+    pub fn rust_triple(&self) -> String {
+        let arch = match self.arch {
+            TargetArch::X86 => "i686",
+            other => other.as_str(),
+        };
+        match self.os {
+            TargetOs::Windows => format!("{arch}-pc-windows-{}", self.abi.as_str()),
+            TargetOs::Macos => format!("{arch}-apple-darwin"),
+            // assumes hard-float; soft-float arm isn't detected
+            TargetOs::Linux if self.arch == TargetArch::Arm => {
+                format!("arm-unknown-linux-{}eabihf", self.abi.as_str())
+            }
+            TargetOs::Linux => format!("{arch}-unknown-linux-{}", self.abi.as_str()),
+            TargetOs::Freebsd => format!("{arch}-unknown-freebsd"),
+            TargetOs::Netbsd => format!("{arch}-unknown-netbsd"),
+        }
+    }
+}
+
+/// Resolves the current platform into a normalized [`TargetTriple`].
+pub fn target_triple() -> Result<TargetTriple> {
+    let os = if cfg!(target_os = "windows") {
+        TargetOs::Windows
     } else if cfg!(target_os = "macos") {
-        "macos"
+        TargetOs::Macos
     } else if cfg!(target_os = "linux") {
-        "linux"
+        TargetOs::Linux
+    } else if cfg!(target_os = "freebsd") {
+        TargetOs::Freebsd
+    } else if cfg!(target_os = "netbsd") {
+        TargetOs::Netbsd
     } else {
         bail!("unsupported OS")
     };
 
-    let target_arch = if cfg!(target_arch = "x86_64") {
-        "x86_64"
+    let arch = if cfg!(target_arch = "x86_64") {
+        TargetArch::X86_64
     } else if cfg!(target_arch = "aarch64") {
-        "aarch64"
+        TargetArch::Aarch64
+    } else if cfg!(target_arch = "arm") {
+        TargetArch::Arm
+    } else if cfg!(target_arch = "x86") {
+        TargetArch::X86
     } else {
         bail!("unsupported target architecture")
     };
-    Ok((target_os, target_arch))
+
+    let abi = if cfg!(target_env = "musl") {
+        TargetAbi::Musl
+    } else if cfg!(target_env = "msvc") {
+        TargetAbi::Msvc
+    } else if cfg!(target_env = "gnu") {
+        TargetAbi::Gnu
+    } else {
+        TargetAbi::None
+    };
+
+    Ok(TargetTriple { os, arch, abi })
+}
+
+/// Thin wrapper around [`target_triple`] for callers that only need OS/arch.
+pub fn os_arch() -> Result<(&'static str, &'static str)> {
+    let target = target_triple()?;
+    Ok((target.os.as_str(), target.arch.as_str()))
 }
 
 pub trait StrAdditions {
@@ -145,25 +269,62 @@ impl PathBufAdditions for PathBuf {
     }
 }
 
+/// A compiler diagnostic's level, rendered text, and primary span location.
+#[derive(Debug, Clone)]
+pub struct RenderedDiagnostic {
+    pub level: DiagnosticLevel,
+    pub rendered: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl From<CompilerMessage> for RenderedDiagnostic {
+    fn from(msg: CompilerMessage) -> Self {
+        let primary_span = msg.message.spans.iter().find(|span| span.is_primary);
+        Self {
+            level: msg.message.level,
+            rendered: msg
+                .message
+                .rendered
+                .unwrap_or_else(|| msg.message.message.clone()),
+            file: primary_span.map(|span| span.file_name.clone()),
+            line: primary_span.map(|span| span.line_start),
+            column: primary_span.map(|span| span.column_start),
+        }
+    }
+}
+
+/// Artifacts and diagnostics collected from one `cargo build`'s JSON stream.
+#[derive(Debug, Default)]
+pub struct BuildOutput {
+    pub artifacts: Vec<Artifact>,
+    /// Every diagnostic cargo emitted, in the order it was received.
+    pub diagnostics: Vec<RenderedDiagnostic>,
+    pub success: bool,
+    /// The first error-level diagnostic, if the build failed.
+    pub errored: Option<RenderedDiagnostic>,
+}
+
 pub trait CommandAdditions {
     /// Sets up the command so that stdout is redirected and parsed by cargo_metadata.
-    /// It returns a handle and a child process. Waiting on the handle returns
-    /// a vector of cargo_metadata Artifacts.
-    fn spawn_cargo_parsed(&mut self) -> Result<(JoinHandle<Vec<Artifact>>, Child)>;
+    /// Spawns the child as the leader of its own process group/job. Waiting on
+    /// the handle returns the build's full `BuildOutput`.
+    fn spawn_cargo_parsed(&mut self) -> Result<(JoinHandle<BuildOutput>, AsyncGroupChild)>;
 }
 
 impl CommandAdditions for Command {
-    fn spawn_cargo_parsed(&mut self) -> Result<(JoinHandle<Vec<Artifact>>, Child)> {
+    fn spawn_cargo_parsed(&mut self) -> Result<(JoinHandle<BuildOutput>, AsyncGroupChild)> {
         let mut process = self
             .stdout(Stdio::piped())
             .arg("--message-format=json-render-diagnostics")
-            .spawn()?;
+            .group_spawn()?;
 
-        let mut stdout = BufReader::new(process.stdout.take().unwrap());
+        let mut stdout = BufReader::new(process.inner().stdout.take().unwrap());
 
         let handle = tokio::spawn(async move {
             let mut line = String::new();
-            let mut artifacts: Vec<Artifact> = Vec::new();
+            let mut output = BuildOutput::default();
             loop {
                 match stdout.read_line(&mut line).await {
                     Ok(_) => {
@@ -171,14 +332,30 @@ impl CommandAdditions for Command {
                         deserializer.disable_recursion_limit();
                         match Message::deserialize(&mut deserializer) {
                             Ok(Message::BuildFinished(v)) => {
+                                output.success = v.success;
                                 if !v.success {
                                     log::warn!("Build failed")
                                 }
                                 break;
                             }
                             Ok(Message::BuildScriptExecuted(_script)) => {}
-                            Ok(Message::CompilerArtifact(art)) => artifacts.push(art),
-                            Ok(Message::CompilerMessage(msg)) => log::info!("MESSAGE {msg:?}"),
+                            Ok(Message::CompilerArtifact(art)) => output.artifacts.push(art),
+                            Ok(Message::CompilerMessage(msg)) => {
+                                let diagnostic: RenderedDiagnostic = msg.into();
+                                if diagnostic.level == DiagnosticLevel::Error
+                                    && output.errored.is_none()
+                                {
+                                    output.errored = Some(diagnostic.clone());
+                                }
+                                // Forwarded live so the browser error-overlay can show
+                                // it immediately instead of waiting for the build to finish.
+                                if let Err(e) =
+                                    MSG_BUS.send(Msg::Diagnostics(vec![diagnostic.clone()]))
+                                {
+                                    log::debug!("Could not send diagnostic to MSG_BUS: {e}");
+                                }
+                                output.diagnostics.push(diagnostic);
+                            }
                             Ok(Message::TextLine(txt)) => log::info!("TEXT {txt:?}"),
                             Err(e) => {
                                 log::error!("cargo stdout: {e}");
@@ -194,7 +371,7 @@ impl CommandAdditions for Command {
                     }
                 }
             }
-            artifacts
+            output
         });
         Ok((handle, process))
     }
@@ -228,7 +405,62 @@ pub fn oneshot_when<S: ToString>(msgs: &'static [Msg], to: S) -> oneshot::Receiv
     rx
 }
 
-pub async fn run_interruptible<S: AsRef<str>>(name: S, mut process: Child) -> Result<()> {
+/// Turns the first Ctrl-C/SIGTERM/SIGHUP into a `Msg::ShutDown` on `MSG_BUS`.
+pub fn listen_for_shutdown_signals() {
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut sigterm =
+                signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+            let mut sighup = signal(SignalKind::hangup()).expect("Could not listen for SIGHUP");
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = sigterm.recv() => {}
+                _ = sighup.recv() => {}
+            };
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+
+        log::info!("Shutting down");
+        if let Err(e) = MSG_BUS.send(Msg::ShutDown) {
+            log::debug!("Could not send ShutDown to MSG_BUS: {e}");
+        }
+    });
+}
+
+/// Kills the whole process tree rooted at `process`: SIGTERM then SIGKILL
+/// after [`SHUTDOWN_GRACE`] on Unix, or the Job Object on Windows.
+async fn kill_process_tree(process: &mut AsyncGroupChild, name: &str) {
+    #[cfg(unix)]
+    {
+        if process.signal(Signal::SIGTERM).is_ok()
+            && tokio::time::timeout(SHUTDOWN_GRACE, process.wait())
+                .await
+                .is_ok()
+        {
+            log::debug!("{name} stopped");
+            return;
+        }
+        log::debug!("{name} did not stop within {SHUTDOWN_GRACE:?}, killing");
+    }
+    match process.kill().await {
+        Ok(_) => log::debug!("{name} stopped"),
+        Err(e) => log::warn!("Could not kill {name}: {e}"),
+    }
+}
+
+static SHUTDOWN_SIGNALS: std::sync::Once = std::sync::Once::new();
+
+pub async fn run_interruptible<S: AsRef<str>>(
+    name: S,
+    mut process: AsyncGroupChild,
+) -> Result<()> {
+    SHUTDOWN_SIGNALS.call_once(listen_for_shutdown_signals);
+
     let stop_rx = oneshot_when(
         &[Msg::SrcChanged, Msg::ShutDown],
         format!("cargo {}", name.as_ref()),
@@ -239,8 +471,7 @@ pub async fn run_interruptible<S: AsRef<str>>(name: S, mut process: Child) -> Re
                 false => return Err(anyhow!("{} failed", name.as_ref())),
         },
         _ = stop_rx => {
-            process.kill().await.map(|_| true).expect("Could not kill process");
-            log::debug!("{} stopped", name.as_ref());
+            kill_process_tree(&mut process, name.as_ref()).await;
             Ok(())
         }
     }